@@ -1,37 +1,257 @@
 use std::convert::{From, Into};
 use std::cmp::{PartialOrd, Ordering};
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::iter::{Sum, Product};
+use std::ops::{Add, Mul};
 
+use num_traits::{One, Zero};
+
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for u8 {}
+    impl Sealed for u16 {}
+    impl Sealed for u32 {}
+}
+
+///
+/// The integer type used to back a `CoarseLogProb`'s quantized storage. Implemented for `u8`,
+/// `u16`, and `u32`, trading resolution for storage size: `u8` gives a 1-byte log probability for
+/// extremely memory-constrained tables at coarse resolution, `u16` is the historical default, and
+/// `u32` gives near-`f32` fidelity at the same 4 bytes, with guaranteed monotone integer ordering
+/// in all cases. This trait is sealed and cannot be implemented outside of this crate.
+///
+pub trait Backing: sealed::Sealed + Copy + Clone + Ord + Hash + Debug {
+
+    /// The largest representable value of this backing type
+    const MAX: Self;
+
+    /// The additive identity of this backing type
+    const ZERO: Self;
+
+    /// `Self::MAX` represented as an f32
+    const MAX_AS_F32: f32;
+
+    /// Converts this value into an f32
+    fn as_f32(self) -> f32;
+
+    /// Rounds and converts an f32 value into this backing type
+    fn from_f32(value: f32) -> Self;
+
+    /// Computes `self + other`, saturating at `Self::MAX` instead of overflowing
+    fn saturating_add(self, other: Self) -> Self;
+}
+
+macro_rules! impl_backing {
+    ($t:ty) => {
+        impl Backing for $t {
+
+            const MAX: $t = <$t>::MAX;
+            const ZERO: $t = 0;
+            const MAX_AS_F32: f32 = <$t>::MAX as f32;
+
+            fn as_f32(self) -> f32 {
+                self as f32
+            }
+
+            fn from_f32(value: f32) -> $t {
+                value as $t
+            }
+
+            fn saturating_add(self, other: $t) -> $t {
+                <$t>::saturating_add(self, other)
+            }
+        }
+    };
+}
+
+impl_backing!(u8);
+impl_backing!(u16);
+impl_backing!(u32);
+
+///
+/// Represents a log probability, quantized into a backing integer type `B` (`u16` by default).
+/// Log probabilities span the range [-87.33655, 0], where the lower bound is taken from the value
+/// `f32::MIN_POSITIVE.ln()`. This representation of log probabilities requires a fraction of the
+/// storage of a single precision f32 value, and is useful in cases where low precision can be
+/// tolerated. See the [`Backing`] trait for the tradeoffs between the supported widths.
 ///
-/// Represents a log probability using "half" precision, which is backed by a u16 value. Log
-/// probabilities span the range [-87.33655, 0], where the lower bound is taken from the value
-/// `f32::MIN_POSITIVE.ln()`. This representation of log probabilities requires half the
-/// amount of storage of a single precision f32 value, and is useful in cases where low precision
-/// can be tolerated.
+/// `CoarseLogProb` is `#[repr(transparent)]` over its backing integer, so with the `bytemuck` or
+/// `zerocopy` features enabled, slices of `CoarseLogProb` can be reinterpreted as slices of that
+/// integer type (and vice versa) with no per-element conversion cost, which is useful for
+/// mmap-ing or otherwise bulk (de)serializing large tables of probabilities (e.g. HMM emission
+/// matrices).
 #[derive(Clone, Copy, Debug, PartialEq)]
-pub struct CoarseLogProb(u16);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[cfg_attr(feature = "zerocopy", derive(zerocopy::AsBytes, zerocopy::FromZeroes, zerocopy::FromBytes))]
+#[repr(transparent)]
+pub struct CoarseLogProb<B = u16>(B);
 
-impl CoarseLogProb {
+impl<B: Backing> CoarseLogProb<B> {
 
     ///
     /// The smallest representable log probability, which is approximately equal to the value
     /// ```f32::MIN_POSITIVE.ln()```
     ///
-    pub const MIN: CoarseLogProb = CoarseLogProb(u16::MAX);
+    pub const MIN: CoarseLogProb<B> = CoarseLogProb(B::MAX);
 
     ///
     /// Represents the unity probability value (1 in real space, 0 in log space)
     ///
-    pub const UNITY: CoarseLogProb = CoarseLogProb(0);
+    pub const UNITY: CoarseLogProb<B> = CoarseLogProb(B::ZERO);
 
     // Minimum value represented as an f32 value
     const MIN_FLOAT_VAL: f32 = -87.33655f32;
-    // Unit of increment for log probability
-    const INCREMENT: f32 = 0.0013326703;
     // Inverse of minimum float value
-    const INV_MFV: f32 = -0.01144996;
+    const INV_MFV: f32 = 1f32 / CoarseLogProb::<B>::MIN_FLOAT_VAL;
+    // Unit of increment for log probability, derived from the width of the backing type
+    const INCREMENT: f32 = -CoarseLogProb::<B>::MIN_FLOAT_VAL / B::MAX_AS_F32;
+
+    ///
+    /// Multiplies two probabilities, which in log space amounts to adding the underlying log
+    /// probabilities. Since a larger backing value represents a smaller probability, this can be
+    /// computed directly on the integers as a saturating add, which naturally clamps the result
+    /// to `CoarseLogProb::MIN` without ever needing to convert back to `f32`.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - the other probability to multiply with this one
+    ///
+    pub fn log_mul(self, other: CoarseLogProb<B>) -> CoarseLogProb<B> {
+        CoarseLogProb(self.0.saturating_add(other.0))
+    }
+
+    ///
+    /// Sums two probabilities using the numerically stable log-sum-exp trick. Given log
+    /// probabilities `x` and `y`, this computes `m = max(x, y)` and returns
+    /// `m + (1 + (min(x, y) - m).exp()).ln()`, which avoids overflow when both values are very
+    /// negative. The result is clamped to `CoarseLogProb::UNITY`, since the sum of two
+    /// probabilities can exceed either individual operand.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - the other probability to sum with this one
+    ///
+    pub fn log_add(self, other: CoarseLogProb<B>) -> CoarseLogProb<B> {
+        let x: f32 = self.into();
+        let y: f32 = other.into();
+        let m = x.max(y);
+        let result = m + (1f32 + (x.min(y) - m).exp()).ln();
+        CoarseLogProb::from(result)
+    }
+
+    ///
+    /// Constructs a `CoarseLogProb` from a raw (non-log) probability, storing `p.ln()`. Unlike
+    /// `From<f32>`, which silently clamps its input, this validates that `p` lies in `[0, 1]` and
+    /// panics otherwise, giving callers a semantic entry point that can't silently accept an
+    /// out-of-range probability. `0.0` maps to `CoarseLogProb::MIN` and `1.0` maps to
+    /// `CoarseLogProb::UNITY`.
+    ///
+    /// # Arguments
+    ///
+    /// * `p` - a raw probability in the range `[0, 1]`
+    ///
+    pub fn from_raw_prob(p: f32) -> CoarseLogProb<B> {
+        assert!((0f32..=1f32).contains(&p), "raw probability must lie in [0, 1], got {}", p);
+        if p == 0f32 {
+            CoarseLogProb::MIN
+        } else if p == 1f32 {
+            CoarseLogProb::UNITY
+        } else {
+            CoarseLogProb::from(p.ln())
+        }
+    }
+
+    ///
+    /// Converts this log probability back into a raw (non-log) probability, i.e. `exp` of the
+    /// decoded log value
+    ///
+    pub fn to_raw_prob(self) -> f32 {
+        let log_val: f32 = self.into();
+        log_val.exp()
+    }
+
+    ///
+    /// Returns the more probable of `self` and `other`, useful for Viterbi-style argmax loops
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - the other probability to compare against
+    ///
+    pub fn max(self, other: CoarseLogProb<B>) -> CoarseLogProb<B> {
+        Ord::max(self, other)
+    }
+
+    ///
+    /// Returns the less probable of `self` and `other`
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - the other probability to compare against
+    ///
+    pub fn min(self, other: CoarseLogProb<B>) -> CoarseLogProb<B> {
+        Ord::min(self, other)
+    }
+}
+
+impl<B: Backing> Add for CoarseLogProb<B> {
+    type Output = CoarseLogProb<B>;
+
+    ///
+    /// Adds two probabilities together via `log_add`
+    ///
+    fn add(self, other: CoarseLogProb<B>) -> CoarseLogProb<B> {
+        self.log_add(other)
+    }
+}
+
+impl<B: Backing> Mul for CoarseLogProb<B> {
+    type Output = CoarseLogProb<B>;
+
+    ///
+    /// Multiplies two probabilities together via `log_mul`
+    ///
+    fn mul(self, other: CoarseLogProb<B>) -> CoarseLogProb<B> {
+        self.log_mul(other)
+    }
+}
+
+impl<B: Backing> Sum for CoarseLogProb<B> {
+
+    ///
+    /// Folds a sequence of log probabilities into the log of their summed probability via
+    /// repeated `log_add`, starting from `CoarseLogProb::MIN` (the impossible-probability identity)
+    ///
+    fn sum<I: Iterator<Item = CoarseLogProb<B>>>(iter: I) -> CoarseLogProb<B> {
+        iter.fold(CoarseLogProb::MIN, |acc, p| acc.log_add(p))
+    }
+}
+
+impl<'a, B: Backing> Sum<&'a CoarseLogProb<B>> for CoarseLogProb<B> {
+    fn sum<I: Iterator<Item = &'a CoarseLogProb<B>>>(iter: I) -> CoarseLogProb<B> {
+        iter.fold(CoarseLogProb::MIN, |acc, p| acc.log_add(*p))
+    }
 }
 
-impl From<f32> for CoarseLogProb {
+impl<B: Backing> Product for CoarseLogProb<B> {
+
+    ///
+    /// Folds a sequence of independent log probabilities into their joint probability via
+    /// repeated `log_mul`, starting from `CoarseLogProb::UNITY` (zero in log space)
+    ///
+    fn product<I: Iterator<Item = CoarseLogProb<B>>>(iter: I) -> CoarseLogProb<B> {
+        iter.fold(CoarseLogProb::UNITY, |acc, p| acc.log_mul(p))
+    }
+}
+
+impl<'a, B: Backing> Product<&'a CoarseLogProb<B>> for CoarseLogProb<B> {
+    fn product<I: Iterator<Item = &'a CoarseLogProb<B>>>(iter: I) -> CoarseLogProb<B> {
+        iter.fold(CoarseLogProb::UNITY, |acc, p| acc.log_mul(*p))
+    }
+}
+
+impl<B: Backing> From<f32> for CoarseLogProb<B> {
 
     ///
     /// Converts an f32 value into a `CoarseLogProb`. Note that values greater than zero will
@@ -42,49 +262,247 @@ impl From<f32> for CoarseLogProb {
     ///
     /// * `value` - an f32 value to be converted to a `CoarseLogProb`
     ///
-    fn from(value: f32) -> CoarseLogProb {
-        if value < CoarseLogProb::MIN_FLOAT_VAL {
+    fn from(value: f32) -> CoarseLogProb<B> {
+        if value < CoarseLogProb::<B>::MIN_FLOAT_VAL {
             CoarseLogProb::MIN
         } else if value >= 0f32 {
             CoarseLogProb::UNITY
         } else {
-            let int = (value * CoarseLogProb::INV_MFV * u16::MAX as f32).round() as u16;
-            CoarseLogProb(int)
+            let int = (value * CoarseLogProb::<B>::INV_MFV * B::MAX_AS_F32).round();
+            CoarseLogProb(B::from_f32(int))
         }
     }
 }
 
-impl Into<f32> for CoarseLogProb {
+impl<B: Backing> From<CoarseLogProb<B>> for f32 {
 
     ///
     /// Converts a `CoarseLogProb` into an f32 value
     ///
-    fn into(self) -> f32 {
-        0f32 - (self.0 as f32 * CoarseLogProb::INCREMENT)
+    fn from(value: CoarseLogProb<B>) -> f32 {
+        0f32 - (value.0.as_f32() * CoarseLogProb::<B>::INCREMENT)
     }
 }
 
-impl PartialOrd for CoarseLogProb {
+impl<B: Backing> PartialOrd for CoarseLogProb<B> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<B: Backing> Eq for CoarseLogProb<B> {}
+
+impl<B: Backing> Ord for CoarseLogProb<B> {
+
+    ///
+    /// The quantization is monotone in the backing integer, so the ordering over `CoarseLogProb`
+    /// is total, but inverted relative to the backing value: a smaller backing value represents
+    /// a *greater* probability
+    ///
+    fn cmp(&self, other: &Self) -> Ordering {
         if self.0 < other.0 {
-            Some(Ordering::Greater)
+            Ordering::Greater
         } else if self.0 > other.0 {
-            Some(Ordering::Less)
+            Ordering::Less
         } else {
-            Some(Ordering::Equal)
+            Ordering::Equal
         }
     }
 }
 
+impl<B: Backing> Hash for CoarseLogProb<B> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl<B: Backing> One for CoarseLogProb<B> {
+
+    ///
+    /// Returns `CoarseLogProb::UNITY`, the multiplicative identity, since products are computed
+    /// as log-adds
+    ///
+    fn one() -> CoarseLogProb<B> {
+        CoarseLogProb::UNITY
+    }
+
+    fn is_one(&self) -> bool {
+        self.0 == B::ZERO
+    }
+}
+
+impl<B: Backing> Zero for CoarseLogProb<B> {
+
+    ///
+    /// Returns `CoarseLogProb::MIN`, the additive identity for probability sums
+    ///
+    fn zero() -> CoarseLogProb<B> {
+        CoarseLogProb::MIN
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0 == B::MAX
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::CoarseLogProb;
+    use num_traits::{One, Zero};
 
     #[test]
     fn test_from_f32() {
-        assert_eq!(CoarseLogProb::from(0f32), CoarseLogProb(0));
-        assert_eq!(CoarseLogProb::from(-10.0f32), CoarseLogProb(7504));
-        assert_eq!(CoarseLogProb::from(-87.33655f32), CoarseLogProb(65535));
-        assert_eq!(CoarseLogProb::from(-100f32), CoarseLogProb(65535));
+        assert_eq!(CoarseLogProb::<u16>::from(0f32), CoarseLogProb(0));
+        assert_eq!(CoarseLogProb::<u16>::from(-10.0f32), CoarseLogProb(7504));
+        assert_eq!(CoarseLogProb::<u16>::from(-87.33655f32), CoarseLogProb(65535));
+        assert_eq!(CoarseLogProb::<u16>::from(-100f32), CoarseLogProb(65535));
+    }
+
+    #[test]
+    fn test_from_f32_u8() {
+        assert_eq!(CoarseLogProb::<u8>::from(0f32), CoarseLogProb(0u8));
+        assert_eq!(CoarseLogProb::<u8>::from(-87.33655f32), CoarseLogProb(255u8));
+        assert_eq!(CoarseLogProb::<u8>::from(-100f32), CoarseLogProb(255u8));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_from_f32_u32() {
+        assert_eq!(CoarseLogProb::<u32>::from(0f32), CoarseLogProb(0u32));
+        assert_eq!(CoarseLogProb::<u32>::from(-87.33655f32), CoarseLogProb(u32::MAX));
+        assert_eq!(CoarseLogProb::<u32>::from(-100f32), CoarseLogProb(u32::MAX));
+    }
+
+    #[test]
+    fn test_log_mul() {
+        let a: CoarseLogProb = CoarseLogProb::from(-10.0f32);
+        let b = CoarseLogProb::from(-20.0f32);
+        assert_eq!(a.log_mul(b), CoarseLogProb::from(-30.0f32));
+        assert_eq!(a * b, CoarseLogProb::from(-30.0f32));
+        assert_eq!(CoarseLogProb::MIN.log_mul(a), CoarseLogProb::MIN);
+    }
+
+    #[test]
+    fn test_log_add() {
+        let a: CoarseLogProb = CoarseLogProb::from(-1.0f32);
+        let b = CoarseLogProb::from(-2.0f32);
+        let sum: f32 = a.log_add(b).into();
+        let expected = ((-1.0f32).exp() + (-2.0f32).exp()).ln();
+        assert!((sum - expected).abs() < 1e-3);
+        assert_eq!(CoarseLogProb::UNITY.log_add(a), CoarseLogProb::UNITY);
+        assert_eq!(a + b, a.log_add(b));
+    }
+
+    #[test]
+    fn test_product() {
+        let probs = [
+            CoarseLogProb::from(-1.0f32),
+            CoarseLogProb::from(-2.0f32),
+            CoarseLogProb::from(-3.0f32),
+        ];
+        let expected: CoarseLogProb = probs[0].log_mul(probs[1]).log_mul(probs[2]);
+        assert_eq!(probs.iter().copied().product::<CoarseLogProb>(), expected);
+        assert_eq!(probs.iter().product::<CoarseLogProb>(), expected);
+    }
+
+    #[test]
+    fn test_sum() {
+        let probs = [
+            CoarseLogProb::from(-1.0f32),
+            CoarseLogProb::from(-2.0f32),
+            CoarseLogProb::from(-3.0f32),
+        ];
+        let expected: CoarseLogProb = probs[0].log_add(probs[1]).log_add(probs[2]);
+        assert_eq!(probs.iter().copied().sum::<CoarseLogProb>(), expected);
+        assert_eq!(probs.iter().sum::<CoarseLogProb>(), expected);
+    }
+
+    #[test]
+    fn test_raw_prob_round_trip() {
+        assert_eq!(CoarseLogProb::<u16>::from_raw_prob(0.0), CoarseLogProb::MIN);
+        assert_eq!(CoarseLogProb::<u16>::from_raw_prob(1.0), CoarseLogProb::UNITY);
+        let p: CoarseLogProb = CoarseLogProb::from_raw_prob(0.5);
+        assert!((p.to_raw_prob() - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_raw_prob_out_of_range() {
+        CoarseLogProb::<u16>::from_raw_prob(1.5);
+    }
+
+    #[test]
+    fn test_one_and_zero() {
+        assert_eq!(CoarseLogProb::<u16>::one(), CoarseLogProb::UNITY);
+        assert_eq!(CoarseLogProb::<u16>::zero(), CoarseLogProb::MIN);
+        assert!(CoarseLogProb::<u16>::UNITY.is_one());
+        assert!(CoarseLogProb::<u16>::MIN.is_zero());
+    }
+
+    #[test]
+    fn test_ord() {
+        let a: CoarseLogProb = CoarseLogProb::from(-1.0f32);
+        let b = CoarseLogProb::from(-2.0f32);
+        assert!(a > b);
+        assert_eq!(a.max(b), a);
+        assert_eq!(a.min(b), b);
+
+        let mut probs = vec![b, a, CoarseLogProb::UNITY];
+        probs.sort_unstable();
+        assert_eq!(probs, vec![b, a, CoarseLogProb::UNITY]);
+    }
+
+    #[test]
+    fn test_ord_collections() {
+        use std::collections::BTreeSet;
+
+        let mut set: BTreeSet<CoarseLogProb> = BTreeSet::new();
+        set.insert(CoarseLogProb::from(-1.0f32));
+        set.insert(CoarseLogProb::from(-1.0f32));
+        set.insert(CoarseLogProb::from(-2.0f32));
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn test_hash() {
+        use std::collections::HashSet;
+
+        let mut set: HashSet<CoarseLogProb> = HashSet::new();
+        assert!(set.insert(CoarseLogProb::from(-1.0f32)));
+        assert!(!set.insert(CoarseLogProb::from(-1.0f32)));
+        assert!(set.insert(CoarseLogProb::from(-2.0f32)));
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&CoarseLogProb::from(-2.0f32)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let original = CoarseLogProb::<u16>::from(-10.0f32);
+        let json = serde_json::to_string(&original).unwrap();
+        let decoded: CoarseLogProb<u16> = serde_json::from_str(&json).unwrap();
+        assert_eq!(original, decoded);
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[test]
+    fn test_bytemuck_cast_slice() {
+        let probs = [
+            CoarseLogProb::<u16>::from(-1.0f32),
+            CoarseLogProb::<u16>::from(-2.0f32),
+        ];
+        let bytes: &[u16] = bytemuck::cast_slice(&probs);
+        let back: &[CoarseLogProb<u16>] = bytemuck::cast_slice(bytes);
+        assert_eq!(back, &probs[..]);
+    }
+
+    #[cfg(feature = "zerocopy")]
+    #[test]
+    fn test_zerocopy_round_trip() {
+        use zerocopy::{AsBytes, FromBytes};
+
+        let original = CoarseLogProb::<u16>::from(-1.0f32);
+        let bytes = original.as_bytes();
+        let decoded = CoarseLogProb::<u16>::read_from(bytes).unwrap();
+        assert_eq!(original, decoded);
+    }
+}
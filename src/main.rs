@@ -1,11 +1,9 @@
-mod lib;
-
-use lib::CoarseLogProb;
+use coarse_log_prob::CoarseLogProb;
 
 fn main() {
 
-    let a = CoarseLogProb::from(-40.0f32);
-    let b = CoarseLogProb::from(-40.001f32);
+    let a: CoarseLogProb = CoarseLogProb::from(-40.0f32);
+    let b: CoarseLogProb = CoarseLogProb::from(-40.001f32);
 
     let av: f32 = a.into();
     let bv: f32 = b.into();